@@ -1,28 +1,117 @@
 use std::env;
-use std::io;
+use std::io::{self, BufRead, Write};
 use std::process;
 
+/// A lex error pinpointing the byte offset in the original pattern where
+/// parsing went wrong, so callers can render a caret diagnostic instead of
+/// unwinding the stack.
+#[derive(Debug)]
+struct LexError {
+    offset: usize,
+    msg: String,
+}
+
+impl LexError {
+    fn new(offset: usize, msg: impl Into<String>) -> Self {
+        LexError {
+            offset,
+            msg: msg.into(),
+        }
+    }
+}
+
+type PResult<T> = Result<T, LexError>;
+
+/// Minimal ANSI coloring helper in the spirit of the `colored` crate: an
+/// extension trait on `str` so call sites read as `"text".green()` instead
+/// of hand-rolled escape codes.
+trait Colorize {
+    fn paint(&self, code: &str) -> String;
+
+    fn green(&self) -> String {
+        self.paint("32")
+    }
+
+    fn bold_red(&self) -> String {
+        self.paint("1;31")
+    }
+}
+
+impl Colorize for str {
+    fn paint(&self, code: &str) -> String {
+        format!("\x1b[{}m{}\x1b[0m", code, self)
+    }
+}
+
+/// Walks a `&str` one char at a time while tracking the byte offset of the
+/// cursor within the original pattern, similar to proc-macro2's `Cursor`.
+struct Cursor<'a> {
+    rest: &'a str,
+    off: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(rest: &'a str, off: usize) -> Self {
+        Cursor { rest, off }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let mut chars = self.rest.chars();
+        let c = chars.next()?;
+        self.rest = chars.as_str();
+        self.off += c.len_utf8();
+        Some(c)
+    }
+
+    fn offset(&self) -> usize {
+        self.off
+    }
+}
+
+/// A postfix repetition applied to a single AST node.
+#[derive(Debug, Clone)]
+enum Quantifier {
+    ZeroOrOne,
+    ZeroOrMore,
+    OneOrMore,
+    Exact(usize),
+    AtLeast(usize),
+    Range(usize, usize),
+}
+
+/// The pattern AST produced by the recursive-descent parser below.
+/// `Alt` holds one `Seq` per `|`-separated branch, so groups can nest
+/// arbitrarily deep and contain classes, quantifiers, and further groups.
 #[derive(Debug, Clone)]
-enum Token {
+enum Node {
     Literal(char),
     Digit,
     Word,
     Wildcard,
     Class(String),
     NegClass(String),
-    OneOrMore(Box<Token>),
-    ZeroOrOne(Box<Token>),
-    Alternation(Vec<String>),
+    Group(Box<Node>),
+    Repeat(Box<Node>, Quantifier),
+    Seq(Vec<Node>),
+    Alt(Vec<Node>),
 }
 
-fn tokenize_pattern(mut pattern: &str) -> (bool, bool, Vec<Token>) {
-    let mut tokens = Vec::new();
+/// Parses a full pattern (after stripping `^`/`$` anchors) into an AST,
+/// using the standard regex precedence: alternation is lowest, then
+/// concatenation, then postfix quantifiers, then atoms/groups.
+fn parse_pattern(mut pattern: &str) -> PResult<(bool, bool, Node)> {
     let mut anchor_start = false;
     let mut anchor_end = false;
+    let mut base = 0usize;
 
     if pattern.starts_with('^') {
         anchor_start = true;
         pattern = &pattern[1..];
+        base += 1;
     }
 
     if pattern.ends_with('$') {
@@ -30,176 +119,775 @@ fn tokenize_pattern(mut pattern: &str) -> (bool, bool, Vec<Token>) {
         pattern = &pattern[..pattern.len() - 1];
     }
 
-    let mut chars = pattern.chars().peekable();
-    while let Some(c) = chars.next() {
-        match c {
-            '+' => {
-                if let Some(last_token) = tokens.pop() {
-                    tokens.push(Token::OneOrMore(Box::new(last_token)));
-                } else {
-                    panic!("'+' cannot be the first token");
-                }
+    let mut cursor = Cursor::new(pattern, base);
+    let node = parse_alt(&mut cursor)?;
+
+    if let Some(c) = cursor.peek() {
+        return Err(LexError::new(cursor.offset(), format!("unexpected '{}'", c)));
+    }
+
+    Ok((anchor_start, anchor_end, node))
+}
+
+/// Parses a `|`-separated list of sequences. Lowest precedence.
+fn parse_alt(cursor: &mut Cursor) -> PResult<Node> {
+    let mut branches = vec![parse_seq(cursor)?];
+
+    while cursor.peek() == Some('|') {
+        cursor.bump();
+        branches.push(parse_seq(cursor)?);
+    }
+
+    if branches.len() == 1 {
+        Ok(branches.into_iter().next().unwrap())
+    } else {
+        Ok(Node::Alt(branches))
+    }
+}
+
+/// Parses a run of concatenated, possibly-quantified atoms, stopping at
+/// `|`, `)`, or the end of input.
+fn parse_seq(cursor: &mut Cursor) -> PResult<Node> {
+    let mut nodes = Vec::new();
+
+    while let Some(c) = cursor.peek() {
+        if c == '|' || c == ')' {
+            break;
+        }
+        nodes.push(parse_quantified(cursor)?);
+    }
+
+    Ok(Node::Seq(nodes))
+}
+
+/// Parses a single atom followed by an optional postfix quantifier.
+fn parse_quantified(cursor: &mut Cursor) -> PResult<Node> {
+    let atom = parse_atom(cursor)?;
+
+    match cursor.peek() {
+        Some('+') => {
+            cursor.bump();
+            Ok(Node::Repeat(Box::new(atom), Quantifier::OneOrMore))
+        }
+        Some('?') => {
+            cursor.bump();
+            Ok(Node::Repeat(Box::new(atom), Quantifier::ZeroOrOne))
+        }
+        Some('*') => {
+            cursor.bump();
+            Ok(Node::Repeat(Box::new(atom), Quantifier::ZeroOrMore))
+        }
+        Some('{') => {
+            let start = cursor.offset();
+            cursor.bump();
+            let quantifier = parse_bound(cursor, start)?;
+            Ok(Node::Repeat(Box::new(atom), quantifier))
+        }
+        _ => Ok(atom),
+    }
+}
+
+/// Parses the inside of `{...}` after the opening brace has been consumed:
+/// `{n}`, `{n,}`, or `{n,m}`.
+fn parse_bound(cursor: &mut Cursor, start: usize) -> PResult<Quantifier> {
+    let n = parse_number(cursor)?;
+
+    match cursor.peek() {
+        Some('}') => {
+            cursor.bump();
+            Ok(Quantifier::Exact(n))
+        }
+        Some(',') => {
+            cursor.bump();
+            if cursor.peek() == Some('}') {
+                cursor.bump();
+                return Ok(Quantifier::AtLeast(n));
             }
-            '?' => {
-                if let Some(last_token) = tokens.pop() {
-                    tokens.push(Token::ZeroOrOne(Box::new(last_token)));
-                } else {
-                    panic!("'?' cannot be the first token");
-                }
+
+            let m = parse_number(cursor)?;
+            if cursor.peek() != Some('}') {
+                return Err(LexError::new(cursor.offset(), "expected '}' to close bound"));
             }
-            '.' => {
-                tokens.push(Token::Wildcard);
-            }
-            '\\' => {
-                if let Some(next) = chars.next() {
-                    match next {
-                        'd' => tokens.push(Token::Digit),
-                        'w' => tokens.push(Token::Word),
-                        '\\' => tokens.push(Token::Literal('\\')),
-                        _ => panic!("Unhandled escape: \\{}", next),
-                    }
-                } else {
-                    panic!("Escape character at end of pattern");
+            cursor.bump();
+            Ok(Quantifier::Range(n, m))
+        }
+        _ => Err(LexError::new(start, "invalid bound, expected '}' or ','")),
+    }
+}
+
+fn parse_number(cursor: &mut Cursor) -> PResult<usize> {
+    let start = cursor.offset();
+    let mut digits = String::new();
+
+    while let Some(c) = cursor.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(c);
+        cursor.bump();
+    }
+
+    digits
+        .parse()
+        .map_err(|_| LexError::new(start, "expected a number in bound"))
+}
+
+/// Parses a single atom: a literal, an escape, a class, or a parenthesized
+/// group. Recurses into `parse_alt` for `(...)`, so nesting depth is just
+/// the recursion depth.
+fn parse_atom(cursor: &mut Cursor) -> PResult<Node> {
+    let start = cursor.offset();
+
+    match cursor.peek() {
+        None => Err(LexError::new(start, "unexpected end of pattern")),
+        Some(c @ ('+' | '?' | '*' | '{')) => {
+            Err(LexError::new(start, format!("'{}' cannot be the first token", c)))
+        }
+        Some('.') => {
+            cursor.bump();
+            Ok(Node::Wildcard)
+        }
+        Some('\\') => {
+            cursor.bump();
+            if let Some(next) = cursor.peek() {
+                cursor.bump();
+                match next {
+                    'd' => Ok(Node::Digit),
+                    'w' => Ok(Node::Word),
+                    '\\' => Ok(Node::Literal('\\')),
+                    other => Err(LexError::new(start, format!("unhandled escape: \\{}", other))),
                 }
+            } else {
+                Err(LexError::new(start, "escape character at end of pattern"))
             }
-            '[' => {
-                let mut class_content = String::new();
-                let mut negated = false;
+        }
+        Some('[') => {
+            cursor.bump();
+            let mut class_content = String::new();
+            let mut negated = false;
 
-                if let Some(&'^') = chars.peek() {
-                    negated = true;
-                    chars.next();
-                }
+            if cursor.peek() == Some('^') {
+                negated = true;
+                cursor.bump();
+            }
 
-                while let Some(ch) = chars.next() {
-                    if ch == ']' {
-                        break;
-                    }
-                    class_content.push(ch);
+            let mut closed = false;
+            while let Some(ch) = cursor.peek() {
+                cursor.bump();
+                if ch == ']' {
+                    closed = true;
+                    break;
                 }
+                class_content.push(ch);
+            }
 
-                if negated {
-                    tokens.push(Token::NegClass(class_content));
-                } else {
-                    tokens.push(Token::Class(class_content));
-                }
+            if !closed {
+                return Err(LexError::new(start, "unterminated character class"));
+            }
+
+            if negated {
+                Ok(Node::NegClass(class_content))
+            } else {
+                Ok(Node::Class(class_content))
+            }
+        }
+        Some('(') => {
+            cursor.bump();
+            let inner = parse_alt(cursor)?;
+
+            if cursor.peek() != Some(')') {
+                return Err(LexError::new(start, "unterminated group"));
+            }
+            cursor.bump();
+
+            Ok(Node::Group(Box::new(inner)))
+        }
+        Some(c) => {
+            cursor.bump();
+            Ok(Node::Literal(c))
+        }
+    }
+}
+
+/// A leaf matcher for a single NFA `Char` instruction, mirroring the leaf
+/// variants of `Node` (groups, sequences, alternation, and quantifiers are
+/// compiled away into `Split`/`Jmp` structure instead).
+#[derive(Debug, Clone)]
+enum CharMatcher {
+    Literal(char),
+    Digit,
+    Word,
+    Wildcard,
+    Class(String),
+    NegClass(String),
+}
+
+impl CharMatcher {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            CharMatcher::Literal(l) => c == *l,
+            CharMatcher::Digit => c.is_ascii_digit(),
+            CharMatcher::Word => c.is_ascii_alphanumeric() || c == '_',
+            CharMatcher::Wildcard => c != '\n',
+            CharMatcher::Class(s) => s.chars().any(|x| c == x),
+            CharMatcher::NegClass(s) => s.chars().all(|x| c != x),
+        }
+    }
+}
+
+/// A single instruction of the compiled NFA program. `Split`/`Jmp` targets
+/// are instruction indices (program counters).
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(CharMatcher),
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+/// Compiles a `Node` AST into an NFA program, following the classic
+/// regex-compile approach: `X+` becomes `L: <X>; Split(L, next)`, `X?`
+/// becomes `Split(body, next); body: <X>`, `X*` becomes a `Split` that
+/// loops back to itself, and `(a|b|c)` becomes chained `Split`s into each
+/// branch that all `Jmp` past. `{n}`/`{n,}`/`{n,m}` expand structurally in
+/// terms of these same building blocks.
+fn compile(node: &Node) -> Vec<Inst> {
+    let mut prog = Vec::new();
+    compile_node(node, &mut prog);
+    prog.push(Inst::Match);
+    prog
+}
+
+fn compile_node(node: &Node, prog: &mut Vec<Inst>) {
+    match node {
+        Node::Literal(c) => prog.push(Inst::Char(CharMatcher::Literal(*c))),
+        Node::Digit => prog.push(Inst::Char(CharMatcher::Digit)),
+        Node::Word => prog.push(Inst::Char(CharMatcher::Word)),
+        Node::Wildcard => prog.push(Inst::Char(CharMatcher::Wildcard)),
+        Node::Class(s) => prog.push(Inst::Char(CharMatcher::Class(s.clone()))),
+        Node::NegClass(s) => prog.push(Inst::Char(CharMatcher::NegClass(s.clone()))),
+        Node::Group(inner) => compile_node(inner, prog),
+        Node::Seq(nodes) => {
+            for n in nodes {
+                compile_node(n, prog);
             }
-            '(' => {
-                let mut alternation_content = String::new();
-                while let Some(ch) = chars.next() {
-                    if ch == ')' {
-                        break;
-                    }
-                    alternation_content.push(ch);
+        }
+        Node::Alt(branches) => compile_alt(branches, prog),
+        Node::Repeat(inner, quantifier) => compile_repeat(inner, quantifier, prog),
+    }
+}
+
+/// Chains `Split`s into each branch, all `Jmp`ing past to a shared end.
+fn compile_alt(branches: &[Node], prog: &mut Vec<Inst>) {
+    let mut jmps = Vec::new();
+
+    for (i, branch) in branches.iter().enumerate() {
+        let is_last = i + 1 == branches.len();
+        if is_last {
+            compile_node(branch, prog);
+            continue;
+        }
+
+        let split_pc = prog.len();
+        prog.push(Inst::Split(0, 0)); // patched below
+        let branch_start = prog.len();
+        compile_node(branch, prog);
+        jmps.push(prog.len());
+        prog.push(Inst::Jmp(0)); // patched once `end` is known
+        let next_alt = prog.len();
+        prog[split_pc] = Inst::Split(branch_start, next_alt);
+    }
+
+    let end = prog.len();
+    for jp in jmps {
+        prog[jp] = Inst::Jmp(end);
+    }
+}
+
+/// Expands a quantified node in terms of `Split`/`Jmp`/repetition.
+fn compile_repeat(inner: &Node, quantifier: &Quantifier, prog: &mut Vec<Inst>) {
+    match quantifier {
+        Quantifier::OneOrMore => {
+            let l = prog.len();
+            compile_node(inner, prog);
+            let split_pc = prog.len();
+            prog.push(Inst::Split(l, split_pc + 1));
+        }
+        Quantifier::ZeroOrOne => {
+            let split_pc = prog.len();
+            prog.push(Inst::Split(0, 0)); // patched once the body and next pc are known
+            let body = prog.len();
+            compile_node(inner, prog);
+            let next = prog.len();
+            prog[split_pc] = Inst::Split(body, next);
+        }
+        Quantifier::ZeroOrMore => {
+            let split_pc = prog.len();
+            prog.push(Inst::Split(0, 0)); // patched once the body and next pc are known
+            let body = prog.len();
+            compile_node(inner, prog);
+            prog.push(Inst::Jmp(split_pc));
+            let next = prog.len();
+            prog[split_pc] = Inst::Split(body, next);
+        }
+        Quantifier::Exact(n) => {
+            for _ in 0..*n {
+                compile_node(inner, prog);
+            }
+        }
+        Quantifier::AtLeast(n) => {
+            for _ in 0..*n {
+                compile_node(inner, prog);
+            }
+            compile_repeat(inner, &Quantifier::ZeroOrMore, prog);
+        }
+        Quantifier::Range(n, m) => {
+            for _ in 0..*n {
+                compile_node(inner, prog);
+            }
+            for _ in *n..*m {
+                compile_repeat(inner, &Quantifier::ZeroOrOne, prog);
+            }
+        }
+    }
+}
+
+/// Adds `pc` and everything reachable from it through `Split`/`Jmp` epsilon
+/// transitions to `list`, using `visited`/`stamp` so each pc is added at most
+/// once per simulation step.
+fn add_thread(prog: &[Inst], pc: usize, list: &mut Vec<usize>, visited: &mut [u32], stamp: u32) {
+    if visited[pc] == stamp {
+        return;
+    }
+    visited[pc] = stamp;
+    match prog[pc] {
+        Inst::Jmp(target) => add_thread(prog, target, list, visited, stamp),
+        Inst::Split(a, b) => {
+            add_thread(prog, a, list, visited, stamp);
+            add_thread(prog, b, list, visited, stamp);
+        }
+        Inst::Char(_) | Inst::Match => list.push(pc),
+    }
+}
+
+/// Simulates the NFA program starting at byte offset `start` in `text`,
+/// advancing two state sets (`clist`, `nlist`) one char at a time. Runs in
+/// O(states * input) time since each pc enters a list at most once per step.
+/// Keeps stepping for as long as any thread is alive and remembers the last
+/// position `Match` was reachable, so the result is leftmost-longest rather
+/// than leftmost-shortest.
+fn simulate(prog: &[Inst], text: &str, start: usize, anchor_end: bool) -> Option<usize> {
+    let mut visited = vec![0u32; prog.len()];
+    let mut stamp = 1u32;
+
+    let mut clist = Vec::new();
+    add_thread(prog, 0, &mut clist, &mut visited, stamp);
+
+    let mut last_match = None;
+    let mut pos = start;
+    for (offset, c) in text[start..].char_indices() {
+        let byte_pos = start + offset;
+        if clist.iter().any(|&pc| matches!(prog[pc], Inst::Match))
+            && (!anchor_end || byte_pos == text.len())
+        {
+            last_match = Some(byte_pos);
+        }
+        if clist.is_empty() {
+            return last_match;
+        }
+
+        stamp += 1;
+        let mut nlist = Vec::new();
+        for &pc in &clist {
+            if let Inst::Char(matcher) = &prog[pc] {
+                if matcher.matches(c) {
+                    add_thread(prog, pc + 1, &mut nlist, &mut visited, stamp);
                 }
-                let options: Vec<String> = alternation_content
-                    .split('|')
-                    .map(|s| s.to_string())
-                    .collect();
-                tokens.push(Token::Alternation(options));
             }
-            _ => tokens.push(Token::Literal(c)),
         }
+        clist = nlist;
+        pos = byte_pos + c.len_utf8();
     }
 
-    (anchor_start, anchor_end, tokens)
+    if clist.iter().any(|&pc| matches!(prog[pc], Inst::Match)) && (!anchor_end || pos == text.len())
+    {
+        last_match = Some(pos);
+    }
+
+    last_match
 }
 
 // see https://www.cs.princeton.edu/courses/archive/spr09/cos333/beautiful.html
-fn match_pattern(text: &str, pattern: &str) -> bool {
-    let (anchor_start, anchor_end, tokens) = tokenize_pattern(pattern);
+/// Returns the byte range of the leftmost match, if any.
+fn match_pattern(text: &str, pattern: &str) -> PResult<Option<(usize, usize)>> {
+    let (anchor_start, anchor_end, node) = parse_pattern(pattern)?;
+    let prog = compile(&node);
 
     if anchor_start {
-        return matchhere(text, &tokens, anchor_end);
+        return Ok(simulate(&prog, text, 0, anchor_end).map(|end| (0, end)));
     }
 
-    for i in 0..text.len() {
-        if matchhere(&text[i..], &tokens, anchor_end) {
-            return true;
+    for (i, _) in text.char_indices() {
+        if let Some(end) = simulate(&prog, text, i, anchor_end) {
+            return Ok(Some((i, end)));
         }
     }
 
-    false
+    // `char_indices` never yields `text.len()`, so a nullable pattern (`a*`,
+    // `q?`, `.*`) would otherwise never get a chance to match an empty line.
+    if let Some(end) = simulate(&prog, text, text.len(), anchor_end) {
+        return Ok(Some((text.len(), end)));
+    }
+
+    Ok(None)
+}
+
+/// A leaf matcher operating on a single byte, used by raw byte mode so
+/// input that is not valid UTF-8 can still be scanned. `Class`/`NegClass`
+/// only consider the ASCII members of the original character class, since
+/// a single byte cannot represent an arbitrary multi-byte class member.
+#[derive(Debug, Clone)]
+enum ByteMatcher {
+    Literal(u8),
+    Digit,
+    Word,
+    Wildcard,
+    Class(Vec<u8>),
+    NegClass(Vec<u8>),
 }
 
-fn matchhere(text: &str, tokens: &[Token], anchor_end: bool) -> bool {
-    if tokens.is_empty() {
-        return !anchor_end || text.is_empty();
+impl ByteMatcher {
+    fn matches(&self, b: u8) -> bool {
+        match self {
+            ByteMatcher::Literal(l) => b == *l,
+            ByteMatcher::Digit => b.is_ascii_digit(),
+            ByteMatcher::Word => b.is_ascii_alphanumeric() || b == b'_',
+            ByteMatcher::Wildcard => b != b'\n',
+            ByteMatcher::Class(s) => s.contains(&b),
+            ByteMatcher::NegClass(s) => !s.contains(&b),
+        }
     }
+}
+
+/// The byte-mode counterpart of `Inst`.
+#[derive(Debug, Clone)]
+enum ByteInst {
+    Byte(ByteMatcher),
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+fn ascii_bytes(s: &str) -> Vec<u8> {
+    s.chars().filter(char::is_ascii).map(|c| c as u8).collect()
+}
+
+/// Compiles a `Node` AST into a byte-mode NFA program. A multi-byte
+/// `Literal` expands into one `Byte` instruction per UTF-8 byte, so
+/// quantifiers and alternation still bracket the whole encoded sequence
+/// correctly.
+fn compile_bytes(node: &Node) -> Vec<ByteInst> {
+    let mut prog = Vec::new();
+    compile_byte_node(node, &mut prog);
+    prog.push(ByteInst::Match);
+    prog
+}
 
-    match &tokens[0] {
-        Token::OneOrMore(inner_token) => {
-            matchoneormore(text, inner_token, &tokens[1..], anchor_end)
+fn compile_byte_node(node: &Node, prog: &mut Vec<ByteInst>) {
+    match node {
+        Node::Literal(c) => {
+            let mut buf = [0u8; 4];
+            for b in c.encode_utf8(&mut buf).as_bytes() {
+                prog.push(ByteInst::Byte(ByteMatcher::Literal(*b)));
+            }
         }
-        Token::ZeroOrOne(inner_token) => {
-            if !text.is_empty() && matchone(text.chars().next().unwrap(), inner_token) {
-                if matchhere(&text[1..], &tokens[1..], anchor_end) {
-                    return true;
-                }
+        Node::Digit => prog.push(ByteInst::Byte(ByteMatcher::Digit)),
+        Node::Word => prog.push(ByteInst::Byte(ByteMatcher::Word)),
+        Node::Wildcard => prog.push(ByteInst::Byte(ByteMatcher::Wildcard)),
+        Node::Class(s) => prog.push(ByteInst::Byte(ByteMatcher::Class(ascii_bytes(s)))),
+        Node::NegClass(s) => prog.push(ByteInst::Byte(ByteMatcher::NegClass(ascii_bytes(s)))),
+        Node::Group(inner) => compile_byte_node(inner, prog),
+        Node::Seq(nodes) => {
+            for n in nodes {
+                compile_byte_node(n, prog);
             }
-            matchhere(text, &tokens[1..], anchor_end)
         }
-        Token::Alternation(options) => {
-            for option in options {
-                if text.starts_with(option)
-                    && matchhere(&text[option.len()..], &tokens[1..], anchor_end)
-                {
-                    return true;
-                }
+        Node::Alt(branches) => compile_alt_bytes(branches, prog),
+        Node::Repeat(inner, quantifier) => compile_repeat_bytes(inner, quantifier, prog),
+    }
+}
+
+/// Byte-mode counterpart of `compile_alt`.
+fn compile_alt_bytes(branches: &[Node], prog: &mut Vec<ByteInst>) {
+    let mut jmps = Vec::new();
+
+    for (i, branch) in branches.iter().enumerate() {
+        let is_last = i + 1 == branches.len();
+        if is_last {
+            compile_byte_node(branch, prog);
+            continue;
+        }
+
+        let split_pc = prog.len();
+        prog.push(ByteInst::Split(0, 0)); // patched below
+        let branch_start = prog.len();
+        compile_byte_node(branch, prog);
+        jmps.push(prog.len());
+        prog.push(ByteInst::Jmp(0)); // patched once `end` is known
+        let next_alt = prog.len();
+        prog[split_pc] = ByteInst::Split(branch_start, next_alt);
+    }
+
+    let end = prog.len();
+    for jp in jmps {
+        prog[jp] = ByteInst::Jmp(end);
+    }
+}
+
+/// Byte-mode counterpart of `compile_repeat`.
+fn compile_repeat_bytes(inner: &Node, quantifier: &Quantifier, prog: &mut Vec<ByteInst>) {
+    match quantifier {
+        Quantifier::OneOrMore => {
+            let l = prog.len();
+            compile_byte_node(inner, prog);
+            let split_pc = prog.len();
+            prog.push(ByteInst::Split(l, split_pc + 1));
+        }
+        Quantifier::ZeroOrOne => {
+            let split_pc = prog.len();
+            prog.push(ByteInst::Split(0, 0)); // patched once the body and next pc are known
+            let body = prog.len();
+            compile_byte_node(inner, prog);
+            let next = prog.len();
+            prog[split_pc] = ByteInst::Split(body, next);
+        }
+        Quantifier::ZeroOrMore => {
+            let split_pc = prog.len();
+            prog.push(ByteInst::Split(0, 0)); // patched once the body and next pc are known
+            let body = prog.len();
+            compile_byte_node(inner, prog);
+            prog.push(ByteInst::Jmp(split_pc));
+            let next = prog.len();
+            prog[split_pc] = ByteInst::Split(body, next);
+        }
+        Quantifier::Exact(n) => {
+            for _ in 0..*n {
+                compile_byte_node(inner, prog);
             }
-            false
         }
-        _ => {
-            if !text.is_empty() && matchone(text.chars().next().unwrap(), &tokens[0]) {
-                matchhere(&text[1..], &tokens[1..], anchor_end)
-            } else {
-                false
+        Quantifier::AtLeast(n) => {
+            for _ in 0..*n {
+                compile_byte_node(inner, prog);
+            }
+            compile_repeat_bytes(inner, &Quantifier::ZeroOrMore, prog);
+        }
+        Quantifier::Range(n, m) => {
+            for _ in 0..*n {
+                compile_byte_node(inner, prog);
+            }
+            for _ in *n..*m {
+                compile_repeat_bytes(inner, &Quantifier::ZeroOrOne, prog);
             }
         }
     }
 }
 
-fn matchoneormore(text: &str, inner_token: &Token, tokens: &[Token], anchor_end: bool) -> bool {
-    if text.is_empty() || !matchone(text.chars().next().unwrap(), inner_token) {
-        return false;
+fn add_thread_bytes(
+    prog: &[ByteInst],
+    pc: usize,
+    list: &mut Vec<usize>,
+    visited: &mut [u32],
+    stamp: u32,
+) {
+    if visited[pc] == stamp {
+        return;
     }
-    for i in 1..text.len() {
-        if matchhere(&text[i..], &tokens[1..], anchor_end) {
-            return true;
+    visited[pc] = stamp;
+    match prog[pc] {
+        ByteInst::Jmp(target) => add_thread_bytes(prog, target, list, visited, stamp),
+        ByteInst::Split(a, b) => {
+            add_thread_bytes(prog, a, list, visited, stamp);
+            add_thread_bytes(prog, b, list, visited, stamp);
+        }
+        ByteInst::Byte(_) | ByteInst::Match => list.push(pc),
+    }
+}
+
+/// The byte-mode counterpart of `simulate`: anchors and all matchers work
+/// on byte offsets/values instead of chars, so arbitrary (possibly
+/// non-UTF-8) input can be scanned. Like `simulate`, it keeps stepping until
+/// every thread dies and returns the last position `Match` was reachable
+/// (leftmost-longest).
+fn simulate_bytes(prog: &[ByteInst], text: &[u8], start: usize, anchor_end: bool) -> Option<usize> {
+    let mut visited = vec![0u32; prog.len()];
+    let mut stamp = 1u32;
+
+    let mut clist = Vec::new();
+    add_thread_bytes(prog, 0, &mut clist, &mut visited, stamp);
+
+    let mut last_match = None;
+    let mut pos = start;
+    for (i, &b) in text[start..].iter().enumerate() {
+        let byte_pos = start + i;
+        if clist.iter().any(|&pc| matches!(prog[pc], ByteInst::Match))
+            && (!anchor_end || byte_pos == text.len())
+        {
+            last_match = Some(byte_pos);
+        }
+        if clist.is_empty() {
+            return last_match;
+        }
+
+        stamp += 1;
+        let mut nlist = Vec::new();
+        for &pc in &clist {
+            if let ByteInst::Byte(matcher) = &prog[pc] {
+                if matcher.matches(b) {
+                    add_thread_bytes(prog, pc + 1, &mut nlist, &mut visited, stamp);
+                }
+            }
         }
+        clist = nlist;
+        pos = byte_pos + 1;
+    }
+
+    if clist.iter().any(|&pc| matches!(prog[pc], ByteInst::Match))
+        && (!anchor_end || pos == text.len())
+    {
+        last_match = Some(pos);
     }
-    false
+
+    last_match
 }
 
-fn matchone(next_char: char, token: &Token) -> bool {
-    match token {
-        Token::Literal(c) => next_char == *c,
-        Token::Digit => next_char.is_ascii_digit(),
-        Token::Word => next_char.is_ascii_alphanumeric() || next_char == '_',
-        Token::Wildcard => next_char != '\n',
-        Token::Class(s) => s.chars().any(|c| next_char == c),
-        Token::NegClass(s) => s.chars().all(|c| next_char != c),
-        _ => panic!("Quantifier token should be handled in matchhere"),
+/// Raw byte mode counterpart of `match_pattern`, usable on input that is
+/// not valid UTF-8.
+fn match_pattern_bytes(text: &[u8], pattern: &str) -> PResult<Option<(usize, usize)>> {
+    let (anchor_start, anchor_end, node) = parse_pattern(pattern)?;
+    let prog = compile_bytes(&node);
+
+    if anchor_start {
+        return Ok(simulate_bytes(&prog, text, 0, anchor_end).map(|end| (0, end)));
+    }
+
+    for i in 0..text.len() {
+        if let Some(end) = simulate_bytes(&prog, text, i, anchor_end) {
+            return Ok(Some((i, end)));
+        }
+    }
+
+    // `0..text.len()` never tries `text.len()` itself, so a nullable pattern
+    // would otherwise never get a chance to match an empty line.
+    if let Some(end) = simulate_bytes(&prog, text, text.len(), anchor_end) {
+        return Ok(Some((text.len(), end)));
+    }
+
+    Ok(None)
+}
+
+/// Prints a diagnostic: the offending pattern, then a colored caret under
+/// the byte offset where the lex error was detected, then the error message.
+fn print_lex_error(pattern: &str, err: &LexError) {
+    eprintln!("{}", pattern);
+    eprintln!("{}{}", " ".repeat(err.offset), "^".bold_red());
+    eprintln!("error: {}", err.msg);
+}
+
+/// Writes the input line to stdout with the matched byte range highlighted
+/// (or, with `only_matching`, just the highlighted match), using raw
+/// `write_all` so non-UTF-8 bytes pass through untouched.
+fn print_byte_match(line: &[u8], start: usize, end: usize, only_matching: bool) {
+    let mut out = io::stdout();
+    if only_matching {
+        out.write_all(b"\x1b[32m").unwrap();
+        out.write_all(&line[start..end]).unwrap();
+        out.write_all(b"\x1b[0m\n").unwrap();
+    } else {
+        out.write_all(&line[..start]).unwrap();
+        out.write_all(b"\x1b[32m").unwrap();
+        out.write_all(&line[start..end]).unwrap();
+        out.write_all(b"\x1b[0m").unwrap();
+        out.write_all(&line[end..]).unwrap();
+        out.write_all(b"\n").unwrap();
     }
 }
 
 fn main() {
-    if env::args().nth(1).unwrap() != "-E" {
+    let args: Vec<String> = env::args().collect();
+
+    let mut only_matching = false;
+    let mut raw_bytes = false;
+    let mut idx = 1;
+    loop {
+        match args.get(idx).map(String::as_str) {
+            Some("-o") => {
+                only_matching = true;
+                idx += 1;
+            }
+            Some("--bytes") => {
+                raw_bytes = true;
+                idx += 1;
+            }
+            _ => break,
+        }
+    }
+
+    if args.get(idx).map(String::as_str) != Some("-E") {
         println!("Expected first argument to be '-E'");
         process::exit(1);
     }
+    idx += 1;
 
-    let pattern = env::args().nth(2).unwrap();
-    let mut input_line = String::new();
+    let pattern = args.get(idx).expect("Expected a pattern argument").clone();
+
+    if raw_bytes {
+        let mut line = Vec::new();
+        io::stdin().lock().read_until(b'\n', &mut line).unwrap();
+        while matches!(line.last(), Some(b'\n') | Some(b'\r')) {
+            line.pop();
+        }
 
+        match match_pattern_bytes(&line, &pattern) {
+            Ok(Some((start, end))) => {
+                print_byte_match(&line, start, end, only_matching);
+                process::exit(0);
+            }
+            Ok(None) => {
+                println!("This is not a match");
+                process::exit(1);
+            }
+            Err(err) => {
+                print_lex_error(&pattern, &err);
+                process::exit(2);
+            }
+        }
+    }
+
+    let mut input_line = String::new();
     io::stdin().read_line(&mut input_line).unwrap();
 
     input_line = input_line.trim_end().to_string();
-    if match_pattern(&input_line, &pattern) {
-        println!("This is a match");
-        process::exit(0);
-    } else {
-        println!("This is not a match");
-        process::exit(1);
+    match match_pattern(&input_line, &pattern) {
+        Ok(Some((start, end))) => {
+            if only_matching {
+                println!("{}", input_line[start..end].green());
+            } else {
+                println!(
+                    "{}{}{}",
+                    &input_line[..start],
+                    input_line[start..end].green(),
+                    &input_line[end..]
+                );
+            }
+            process::exit(0);
+        }
+        Ok(None) => {
+            println!("This is not a match");
+            process::exit(1);
+        }
+        Err(err) => {
+            print_lex_error(&pattern, &err);
+            process::exit(2);
+        }
     }
 }